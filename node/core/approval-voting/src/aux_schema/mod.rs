@@ -37,14 +37,15 @@ use sc_client_api::backend::AuxStore;
 use polkadot_node_primitives::approval::{DelayTranche, RelayVRF};
 use polkadot_primitives::v1::{
 	ValidatorIndex, GroupIndex, CandidateReceipt, SessionIndex, CoreIndex,
-	BlockNumber, Hash, CandidateHash,
+	BlockNumber, Hash, CandidateHash, BlakeTwo256, HashT, Id as ParaId,
 };
 use sp_consensus_slots::Slot;
 use parity_scale_codec::{Encode, Decode};
 
-use std::collections::{BTreeMap, HashMap};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::collections::hash_map::Entry;
-use bitvec::{vec::BitVec, order::Lsb0 as BitOrderLsb0};
+use bitvec::{vec::BitVec, slice::BitSlice, order::Lsb0 as BitOrderLsb0};
 
 use super::Tick;
 
@@ -58,7 +59,7 @@ const STORED_BLOCKS_KEY: &[u8] = b"Approvals_StoredBlocks";
 pub(crate) struct TrancheEntry {
 	tranche: DelayTranche,
 	// Assigned validators, and the instant we received their assignment, rounded
-	// to the nearest tick.
+	// to the nearest tick. Sorted ascending by validator index.
 	assignments: Vec<(ValidatorIndex, Tick)>,
 }
 
@@ -66,6 +67,7 @@ pub(crate) struct TrancheEntry {
 /// particular block.
 #[derive(Debug, Clone, Encode, Decode, PartialEq)]
 pub(crate) struct ApprovalEntry {
+	// Sorted ascending by tranche.
 	tranches: Vec<TrancheEntry>,
 	backing_group: GroupIndex,
 	// When the next wakeup for this entry should occur. This is either to
@@ -77,10 +79,132 @@ pub(crate) struct ApprovalEntry {
 	approved: bool,
 }
 
+/// The status of a single assignment, relative to some tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AssignmentStatus {
+	/// The assigned validator has approved the candidate.
+	Approved,
+	/// The assigned validator has not approved the candidate yet, but has until
+	/// the `deadline` tick to do so before being considered a no-show.
+	Pending {
+		/// The tick at which the assignment becomes a no-show.
+		deadline: Tick,
+	},
+	/// The assigned validator has not approved the candidate in time.
+	NoShow,
+}
+
+impl ApprovalEntry {
+	/// Import an assignment of a validator in some tranche, received at the given tick.
+	///
+	/// Tranches and the assignments within them are kept in canonical order, so the
+	/// encoding of the entry doesn't depend on the order of imports.
+	///
+	/// Returns `false` and leaves the entry untouched if the validator index is out of
+	/// bounds or the validator already has an assignment in any tranche.
+	pub(crate) fn import_assignment(
+		&mut self,
+		tranche: DelayTranche,
+		validator: ValidatorIndex,
+		tick_now: Tick,
+	) -> bool {
+		match self.assignments.get(validator as usize).map(|b| *b) {
+			None | Some(true) => return false,
+			Some(false) => {}
+		}
+
+		let idx = match self.tranches.binary_search_by_key(&tranche, |t| t.tranche) {
+			Ok(idx) => idx,
+			Err(idx) => {
+				self.tranches.insert(idx, TrancheEntry {
+					tranche,
+					assignments: Vec::new(),
+				});
+
+				idx
+			}
+		};
+
+		let assignments = &mut self.tranches[idx].assignments;
+		if let Err(pos) = assignments.binary_search_by_key(&validator, |&(v, _)| v) {
+			assignments.insert(pos, (validator, tick_now));
+		}
+
+		self.assignments.set(validator as usize, true);
+
+		true
+	}
+
+	/// Iterate over all assignments, in tranche order, along with their status.
+	///
+	/// An unapproved assignment received at tick `t` is a no-show as-of any
+	/// `tick_now >= t + no_show_duration`.
+	pub(crate) fn assignments_with_status<'a>(
+		&'a self,
+		approvals: &'a BitSlice<BitOrderLsb0, u8>,
+		tick_now: Tick,
+		no_show_duration: Tick,
+	) -> impl Iterator<Item = (ValidatorIndex, DelayTranche, AssignmentStatus)> + 'a {
+		self.tranches.iter().flat_map(move |tranche_entry| {
+			let tranche = tranche_entry.tranche;
+			tranche_entry.assignments.iter().map(move |&(validator, tick)| {
+				let approved = approvals.get(validator as usize).map_or(false, |b| *b);
+				let deadline = tick + no_show_duration;
+
+				let status = if approved {
+					AssignmentStatus::Approved
+				} else if deadline <= tick_now {
+					AssignmentStatus::NoShow
+				} else {
+					AssignmentStatus::Pending { deadline }
+				};
+
+				(validator, tranche, status)
+			})
+		})
+	}
+
+	/// The validators which are currently no-shows, in tranche order.
+	///
+	/// See `assignments_with_status` for the definition of a no-show.
+	pub(crate) fn no_show_validators(
+		&self,
+		approvals: &BitSlice<BitOrderLsb0, u8>,
+		tick_now: Tick,
+		no_show_duration: Tick,
+	) -> Vec<ValidatorIndex> {
+		self.assignments_with_status(approvals, tick_now, no_show_duration)
+			.filter(|&(_, _, status)| status == AssignmentStatus::NoShow)
+			.map(|(validator, _, _)| validator)
+			.collect()
+	}
+}
+
+/// The parts of a candidate receipt which are needed to reference the candidate.
+///
+/// The full receipt is stored under a separate key, as it is only rarely needed
+/// and is large compared to the rest of the candidate entry.
+#[derive(Debug, Clone, Default, Encode, Decode, PartialEq)]
+pub(crate) struct CandidateSummary {
+	hash: CandidateHash,
+	para_id: ParaId,
+	relay_parent: Hash,
+}
+
+impl CandidateSummary {
+	fn new(hash: CandidateHash, candidate: &CandidateReceipt) -> Self {
+		CandidateSummary {
+			hash,
+			para_id: candidate.descriptor.para_id,
+			relay_parent: candidate.descriptor.relay_parent,
+		}
+	}
+}
+
 /// Metadata regarding approval of a particular candidate.
 #[derive(Debug, Clone, Encode, Decode, PartialEq)]
 pub(crate) struct CandidateEntry {
-	candidate: CandidateReceipt,
+	summary: CandidateSummary,
 	session: SessionIndex,
 	// Assignments are based on blocks, so we need to track assignments separately
 	// based on the block we are looking at.
@@ -88,11 +212,86 @@ pub(crate) struct CandidateEntry {
 	approvals: BitVec<BitOrderLsb0, u8>,
 }
 
+impl CandidateEntry {
+	/// Load the full candidate receipt from the aux store.
+	///
+	/// The receipt is written alongside the candidate entry and deleted along with it,
+	/// so this only returns `None` for a corrupt DB.
+	pub(crate) fn receipt(&self, store: &impl AuxStore)
+		-> sp_blockchain::Result<Option<CandidateReceipt>>
+	{
+		load_decode(store, &candidate_receipt_key(&self.summary.hash))
+	}
+
+	/// The hashes of all blocks the candidate has an approval entry under, in ascending order.
+	pub(crate) fn blocks(&self) -> impl Iterator<Item = &Hash> {
+		self.block_assignments.keys()
+	}
+
+	/// Remove the approval entry under the given block, if any.
+	/// Returns `true` if the candidate entry has no remaining approval entries.
+	pub(crate) fn remove_block(&mut self, block_hash: &Hash) -> bool {
+		self.block_assignments.remove(block_hash);
+		self.block_assignments.is_empty()
+	}
+
+	/// Whether the candidate entry has no approval entries under any block.
+	pub(crate) fn is_empty(&self) -> bool {
+		self.block_assignments.is_empty()
+	}
+
+	/// Retain only the approval entries under blocks for which the predicate returns `true`.
+	pub(crate) fn retain_blocks(&mut self, mut predicate: impl FnMut(&Hash) -> bool) {
+		let removed: Vec<_> = self.block_assignments.keys()
+			.filter(|h| !predicate(*h))
+			.cloned()
+			.collect();
+
+		for h in removed {
+			self.block_assignments.remove(&h);
+		}
+	}
+
+	/// The indices of all validators which have approved the candidate, in ascending order.
+	pub(crate) fn approved_validators(&self) -> impl Iterator<Item = ValidatorIndex> + '_ {
+		self.approvals.iter()
+			.enumerate()
+			.filter(|(_, approved)| **approved)
+			.map(|(i, _)| i as ValidatorIndex)
+	}
+
+	/// The number of approvals and the total number of validators, without
+	/// cloning the approvals bitfield.
+	pub(crate) fn approvals_count(&self) -> (usize, usize) {
+		(self.approvals.count_ones(), self.approvals.len())
+	}
+
+	/// Whether the given validator has approved the candidate.
+	pub(crate) fn has_approval(&self, validator: ValidatorIndex) -> bool {
+		self.approvals.get(validator as usize).map_or(false, |b| *b)
+	}
+
+	/// The validators which are both assigned to the candidate under the given
+	/// approval entry and have approved it, in ascending order.
+	///
+	/// These are the validators whose approvals count towards approval of the
+	/// candidate under the approval entry's block.
+	pub(crate) fn approving_set(&self, approval_entry: &ApprovalEntry) -> Vec<ValidatorIndex> {
+		self.approvals.iter()
+			.zip(approval_entry.assignments.iter())
+			.enumerate()
+			.filter(|(_, (approved, assigned))| **approved && **assigned)
+			.map(|(i, _)| i as ValidatorIndex)
+			.collect()
+	}
+}
+
 /// Metadata regarding approval of a particular block, by way of approval of the
 /// candidates contained within it.
 #[derive(Debug, Clone, Encode, Decode, PartialEq)]
 pub(crate) struct BlockEntry {
 	block_hash: Hash,
+	parent_hash: Hash,
 	session: SessionIndex,
 	slot: Slot,
 	relay_vrf_story: RelayVRF,
@@ -103,6 +302,7 @@ pub(crate) struct BlockEntry {
 	// The i'th bit is `true` iff the candidate has been approved in the context of this
 	// block. The block can be considered approved if the bitfield has all bits set to `true`.
 	approved_bitfield: BitVec<BitOrderLsb0, u8>,
+	// The children of this block which have entries in the DB. Sorted ascending.
 	children: Vec<Hash>,
 }
 
@@ -165,7 +365,7 @@ pub(crate) fn canonicalize(
 				}
 			};
 
-			candidate.block_assignments.remove(&block_hash);
+			candidate.remove_block(&block_hash);
 		}
 
 		Ok(block_entry.children)
@@ -240,8 +440,9 @@ pub(crate) fn canonicalize(
 		let mut deleted = Vec::new();
 
 		for (candidate_hash, candidate) in visited_candidates {
-			if candidate.block_assignments.is_empty() {
+			if candidate.is_empty() {
 				deleted.push(candidate_entry_key(&candidate_hash));
+				deleted.push(candidate_receipt_key(&candidate_hash));
 			} else {
 				written.push((candidate_entry_key(&candidate_hash), candidate.encode()));
 			}
@@ -318,6 +519,7 @@ pub(crate) fn clear(store: &impl AuxStore)
 
 			for &(_, candidate_hash) in &block_entry.candidates {
 				visited_candidate_keys.push(candidate_entry_key(&candidate_hash));
+				visited_candidate_keys.push(candidate_receipt_key(&candidate_hash));
 			}
 		}
 	}
@@ -334,6 +536,138 @@ pub(crate) fn clear(store: &impl AuxStore)
 	Ok(())
 }
 
+/// An overlay over an aux store, which buffers all writes in memory.
+///
+/// Reads are served from the buffered writes where possible and fall through to the
+/// underlying store otherwise. The buffered writes can either be committed to the
+/// underlying store in a single batch or discarded by dropping the overlay.
+///
+/// This allows speculative evaluation of any of the functions in this module without
+/// touching the underlying store.
+pub(crate) struct OverlayedBackend<'a, S> {
+	inner: &'a S,
+	// `None` marks a deleted key.
+	overlay: RefCell<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl<'a, S: AuxStore> OverlayedBackend<'a, S> {
+	/// Create a new, empty overlay over the given store.
+	pub(crate) fn new(inner: &'a S) -> Self {
+		OverlayedBackend {
+			inner,
+			overlay: RefCell::new(BTreeMap::new()),
+		}
+	}
+
+	/// Whether there are no buffered writes.
+	pub(crate) fn is_empty(&self) -> bool {
+		self.overlay.borrow().is_empty()
+	}
+
+	/// Consume the overlay, yielding the buffered insertions and deletions,
+	/// both sorted by key.
+	pub(crate) fn into_write_ops(self) -> (Vec<(Vec<u8>, Vec<u8>)>, Vec<Vec<u8>>) {
+		let mut insertions = Vec::new();
+		let mut deletions = Vec::new();
+
+		for (k, v) in self.overlay.into_inner() {
+			match v {
+				Some(v) => insertions.push((k, v)),
+				None => deletions.push(k),
+			}
+		}
+
+		(insertions, deletions)
+	}
+
+	/// Write all buffered changes to the underlying store.
+	pub(crate) fn commit(self) -> sp_blockchain::Result<()> {
+		let inner = self.inner;
+		let (insertions, deletions) = self.into_write_ops();
+
+		// Because aux-store requires &&[u8], we have to collect.
+		let inserted_keys: Vec<_> = insertions.iter().map(|&(ref k, ref v)| (&k[..], &v[..])).collect();
+		let deleted_keys: Vec<_> = deletions.iter().map(|k| &k[..]).collect();
+
+		inner.insert_aux(&inserted_keys, &deleted_keys)
+	}
+}
+
+impl<'a, S: AuxStore> AuxStore for OverlayedBackend<'a, S> {
+	fn insert_aux<'b, 'c: 'b, 'd: 'b, I, D>(&self, insertions: I, deletions: D) -> sp_blockchain::Result<()>
+		where I: IntoIterator<Item = &'b (&'d [u8], &'d [u8])>, D: IntoIterator<Item = &'b &'c [u8]>
+	{
+		let mut overlay = self.overlay.borrow_mut();
+
+		// insertions before deletions, as in the underlying store.
+		for (k, v) in insertions {
+			overlay.insert(k.to_vec(), Some(v.to_vec()));
+		}
+
+		for k in deletions {
+			overlay.insert(k.to_vec(), None);
+		}
+
+		Ok(())
+	}
+
+	fn get_aux(&self, key: &[u8]) -> sp_blockchain::Result<Option<Vec<u8>>> {
+		match self.overlay.borrow().get(key) {
+			Some(v) => Ok(v.clone()),
+			None => self.inner.get_aux(key),
+		}
+	}
+}
+
+/// Repair the `approved_bitfield` of every stored block entry, using the `approved` flag of
+/// the approval entries under the block as the source of truth.
+///
+/// Candidates without a candidate entry or without an approval entry under the block are
+/// considered unapproved. Each repaired block entry is written on its own, so the work done
+/// per write is bounded by the candidates of a single block. Returns the hashes of all block
+/// entries which were repaired.
+///
+/// This is not part of start-up, as the aux store is cleared then. It is intended for
+/// consistency checks over a live store.
+pub(crate) fn recover_approved_bitfields(store: &impl AuxStore)
+	-> sp_blockchain::Result<Vec<Hash>>
+{
+	let range = match load_stored_blocks(store)? {
+		None => return Ok(Vec::new()),
+		Some(range) => range,
+	};
+
+	let mut repaired = Vec::new();
+
+	for i in range.0..range.1 {
+		for block_hash in load_blocks_at_height(store, i)? {
+			let mut block_entry = match load_block_entry(store, &block_hash)? {
+				None => continue,
+				Some(e) => e,
+			};
+
+			let candidate_hashes: Vec<_> = block_entry.candidates.iter().map(|&(_, h)| h).collect();
+			let approved: BitVec<BitOrderLsb0, u8> = load_candidate_entries(store, &candidate_hashes)?
+				.into_iter()
+				.map(|c| c.and_then(|c| c.block_assignments.get(&block_hash).map(|a| a.approved)))
+				.map(|approved| approved.unwrap_or(false))
+				.collect();
+
+			if block_entry.approved_bitfield != approved {
+				block_entry.approved_bitfield = approved;
+
+				let key = block_entry_key(&block_hash);
+				let value = block_entry.encode();
+				store.insert_aux(&[(&key[..], &value[..])], &[])?;
+
+				repaired.push(block_hash);
+			}
+		}
+	}
+
+	Ok(repaired)
+}
+
 fn load_decode<D: Decode>(store: &impl AuxStore, key: &[u8])
 	-> sp_blockchain::Result<Option<D>>
 {
@@ -365,16 +699,22 @@ pub(crate) struct NewCandidateInfo {
 ///
 /// Has no effect if there is already an entry for the block or `candidate_info` returns
 /// `None` for any of the candidates referenced by the block entry.
+///
+/// The `approved_bitfield` of the block entry is resized to the number of candidates
+/// before writing, with any added bits set to `false`.
 pub(crate) fn add_block_entry(
 	store: &impl AuxStore,
-	parent_hash: Hash,
 	number: BlockNumber,
-	entry: BlockEntry,
+	mut entry: BlockEntry,
 	n_validators: usize,
 	candidate_info: impl Fn(&CandidateHash) -> Option<NewCandidateInfo>,
 ) -> sp_blockchain::Result<()> {
 	let session = entry.session;
 
+	// The i'th bit of the bitfield must correspond to the i'th candidate,
+	// so a bitfield of any other length is never valid.
+	entry.approved_bitfield.resize(entry.candidates.len(), false);
+
 	let new_block_range = {
 		let new_range = match load_stored_blocks(store)? {
 			None => Some(StoredBlockRange(number, number + 1)),
@@ -411,13 +751,19 @@ pub(crate) fn add_block_entry(
 				Some(info) => info,
 			};
 
-			let mut candidate_entry = load_candidate_entry(store, &candidate_hash)?
-				.unwrap_or_else(move || CandidateEntry {
-					candidate,
-					session,
-					block_assignments: BTreeMap::new(),
-					approvals: bitvec::bitvec![BitOrderLsb0, u8; 0; n_validators],
-				});
+			let (mut candidate_entry, write_receipt) = match load_candidate_entry(store, &candidate_hash)? {
+				Some(c) => (c, None),
+				None => {
+					let candidate_entry = CandidateEntry {
+						summary: CandidateSummary::new(*candidate_hash, &candidate),
+						session,
+						block_assignments: BTreeMap::new(),
+						approvals: bitvec::bitvec![BitOrderLsb0, u8; 0; n_validators],
+					};
+
+					(candidate_entry, Some((candidate_receipt_key(candidate_hash), candidate.encode())))
+				}
+			};
 
 			candidate_entry.block_assignments.insert(
 				entry.block_hash,
@@ -434,14 +780,19 @@ pub(crate) fn add_block_entry(
 			updated_entries.push(
 				(candidate_entry_key(&candidate_hash), candidate_entry.encode())
 			);
+			updated_entries.extend(write_receipt);
 		}
 
 		updated_entries
 	};
 
 	let updated_parent = {
+		let parent_hash = entry.parent_hash;
 		load_block_entry(store, &parent_hash)?.map(|mut e| {
-			e.children.push(entry.block_hash);
+			if let Err(pos) = e.children.binary_search(&entry.block_hash) {
+				e.children.insert(pos, entry.block_hash);
+			}
+
 			(block_entry_key(&parent_hash), e.encode())
 		})
 	};
@@ -451,7 +802,7 @@ pub(crate) fn add_block_entry(
 	// write:
 	//   - new block range
 	//   - updated blocks-at item
-	//   - fresh and updated candidate entries
+	//   - fresh and updated candidate entries, and receipts of fresh candidates
 	//   - the parent block entry.
 	//   - the block entry itself
 
@@ -469,6 +820,30 @@ pub(crate) fn add_block_entry(
 	Ok(())
 }
 
+/// Load the hashes of all descendants of the given block, by following the
+/// `children` of block entries transitively.
+///
+/// The root itself is not included. Descendants are returned in breadth-first order.
+pub(crate) fn descendants(store: &impl AuxStore, root: &Hash)
+	-> sp_blockchain::Result<Vec<Hash>>
+{
+	let mut descendants = Vec::new();
+	let mut frontier = match load_block_entry(store, root)? {
+		None => return Ok(descendants),
+		Some(e) => VecDeque::from(e.children),
+	};
+
+	while let Some(block_hash) = frontier.pop_front() {
+		descendants.push(block_hash);
+
+		if let Some(e) = load_block_entry(store, &block_hash)? {
+			frontier.extend(e.children);
+		}
+	}
+
+	Ok(descendants)
+}
+
 /// Load the stored-blocks key from the state.
 pub(crate) fn load_stored_blocks(store: &impl AuxStore)
 	-> sp_blockchain::Result<Option<StoredBlockRange>>
@@ -497,6 +872,322 @@ pub(crate) fn load_candidate_entry(store: &impl AuxStore, candidate_hash: &Candi
 	load_decode(store, &candidate_entry_key(candidate_hash))
 }
 
+/// Load a candidate entry along with its approval entry for a specific block.
+///
+/// Returns `None` if either the candidate entry or the approval entry under the
+/// given block are missing.
+pub(crate) fn load_candidate_with_approval(
+	store: &impl AuxStore,
+	block_hash: &Hash,
+	candidate_hash: &CandidateHash,
+) -> sp_blockchain::Result<Option<(CandidateEntry, ApprovalEntry)>> {
+	let candidate_entry = match load_candidate_entry(store, candidate_hash)? {
+		None => return Ok(None),
+		Some(c) => c,
+	};
+
+	let approval_entry = match candidate_entry.block_assignments.get(block_hash) {
+		None => return Ok(None),
+		Some(a) => a.clone(),
+	};
+
+	Ok(Some((candidate_entry, approval_entry)))
+}
+
+/// Load the number of distinct validators which have approved at least one of the
+/// candidates in the block.
+///
+/// Candidates without a candidate entry are ignored.
+pub(crate) fn load_approving_validators_count(store: &impl AuxStore, block_entry: &BlockEntry)
+	-> sp_blockchain::Result<usize>
+{
+	let candidate_hashes: Vec<_> = block_entry.candidates.iter().map(|&(_, h)| h).collect();
+
+	let mut union: BitVec<BitOrderLsb0, u8> = BitVec::new();
+	for candidate_entry in load_candidate_entries(store, &candidate_hashes)? {
+		let candidate_entry = match candidate_entry {
+			None => continue,
+			Some(c) => c,
+		};
+
+		if union.len() < candidate_entry.approvals.len() {
+			union.resize(candidate_entry.approvals.len(), false);
+		}
+
+		union |= candidate_entry.approvals;
+	}
+
+	Ok(union.count_ones())
+}
+
+/// Compute a hash over all approval data stored for a block: the block entry itself,
+/// and, for each of its candidates in order, the approval entry for the block as well
+/// as the approvals of the candidate.
+///
+/// As the stored encodings are canonical, two stores holding the same approval data
+/// for a block produce the same hash. Returns `None` if there is no entry for the block.
+pub(crate) fn state_hash(store: &impl AuxStore, block_hash: &Hash)
+	-> sp_blockchain::Result<Option<Hash>>
+{
+	let block_entry = match load_block_entry(store, block_hash)? {
+		None => return Ok(None),
+		Some(b) => b,
+	};
+
+	let mut candidates = Vec::with_capacity(block_entry.candidates.len());
+	for &(_, ref candidate_hash) in &block_entry.candidates {
+		let approval_data = load_candidate_entry(store, candidate_hash)?.map(|c| {
+			let approval_entry = c.block_assignments.get(block_hash).cloned();
+			(approval_entry, c.approvals)
+		});
+
+		candidates.push((*candidate_hash, approval_data));
+	}
+
+	Ok(Some(BlakeTwo256::hash_of(&(block_entry, candidates))))
+}
+
+/// A single difference between the approval data of two stores, `a` and `b`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum StateDifference {
+	/// The stored block ranges differ.
+	StoredBlocks {
+		a: Option<StoredBlockRange>,
+		b: Option<StoredBlockRange>,
+	},
+	/// A block entry is present in only one of the stores.
+	MissingBlock {
+		block_hash: Hash,
+		in_a: bool,
+	},
+	/// The approved bitfields of a block differ.
+	ApprovedBitfield {
+		block_hash: Hash,
+		a: BitVec<BitOrderLsb0, u8>,
+		b: BitVec<BitOrderLsb0, u8>,
+	},
+	/// The block entries differ in something other than the approved bitfield.
+	BlockEntry {
+		block_hash: Hash,
+	},
+	/// A candidate entry is present in only one of the stores.
+	MissingCandidate {
+		candidate_hash: CandidateHash,
+		in_a: bool,
+	},
+	/// The approvals of a candidate differ.
+	Approvals {
+		candidate_hash: CandidateHash,
+		only_in_a: Vec<ValidatorIndex>,
+		only_in_b: Vec<ValidatorIndex>,
+	},
+	/// An approval entry for a candidate under a block is present in only one of the stores.
+	MissingApprovalEntry {
+		candidate_hash: CandidateHash,
+		block_hash: Hash,
+		in_a: bool,
+	},
+	/// The assignments, as `(tranche, validator)` pairs, of a candidate under a block differ.
+	Assignments {
+		candidate_hash: CandidateHash,
+		block_hash: Hash,
+		only_in_a: Vec<(DelayTranche, ValidatorIndex)>,
+		only_in_b: Vec<(DelayTranche, ValidatorIndex)>,
+	},
+	/// The approval entries of a candidate under a block differ in something other
+	/// than the assignments.
+	ApprovalEntry {
+		candidate_hash: CandidateHash,
+		block_hash: Hash,
+	},
+}
+
+/// Compute the differences between the approval data of two stores.
+///
+/// Blocks are visited in order of height, as per the stored block ranges of both stores,
+/// and blocks with the same `state_hash` in both stores are skipped. An empty result means
+/// there are no differences in the approval data.
+pub(crate) fn diff(a: &impl AuxStore, b: &impl AuxStore)
+	-> sp_blockchain::Result<Vec<StateDifference>>
+{
+	let mut differences = Vec::new();
+
+	let range_a = load_stored_blocks(a)?;
+	let range_b = load_stored_blocks(b)?;
+
+	let mut block_hashes = Vec::new();
+	{
+		let mut seen = HashSet::new();
+		let heights = range_a.iter().chain(range_b.iter()).flat_map(|r| r.0..r.1);
+		let heights: BTreeSet<_> = heights.collect();
+
+		for height in heights {
+			for store_blocks in vec![load_blocks_at_height(a, height)?, load_blocks_at_height(b, height)?] {
+				for block_hash in store_blocks {
+					if seen.insert(block_hash) {
+						block_hashes.push(block_hash);
+					}
+				}
+			}
+		}
+	}
+
+	if range_a != range_b {
+		differences.push(StateDifference::StoredBlocks { a: range_a, b: range_b });
+	}
+
+	let mut visited_candidates = HashSet::new();
+	for block_hash in block_hashes {
+		let (block_a, block_b) = match (load_block_entry(a, &block_hash)?, load_block_entry(b, &block_hash)?) {
+			(None, None) => continue,
+			(Some(_), None) => {
+				differences.push(StateDifference::MissingBlock { block_hash, in_a: true });
+				continue
+			}
+			(None, Some(_)) => {
+				differences.push(StateDifference::MissingBlock { block_hash, in_a: false });
+				continue
+			}
+			(Some(block_a), Some(block_b)) => (block_a, block_b),
+		};
+
+		if state_hash(a, &block_hash)? == state_hash(b, &block_hash)? {
+			continue
+		}
+
+		if block_a.approved_bitfield != block_b.approved_bitfield {
+			differences.push(StateDifference::ApprovedBitfield {
+				block_hash,
+				a: block_a.approved_bitfield.clone(),
+				b: block_b.approved_bitfield.clone(),
+			});
+		}
+
+		{
+			let mut block_a = block_a.clone();
+			block_a.approved_bitfield = block_b.approved_bitfield.clone();
+			if block_a != block_b {
+				differences.push(StateDifference::BlockEntry { block_hash });
+			}
+		}
+
+		let mut candidate_hashes: Vec<_> = block_a.candidates.iter().map(|&(_, h)| h).collect();
+		for &(_, h) in &block_b.candidates {
+			if !candidate_hashes.contains(&h) {
+				candidate_hashes.push(h);
+			}
+		}
+
+		for candidate_hash in candidate_hashes {
+			let first_visit = visited_candidates.insert(candidate_hash);
+
+			let (candidate_a, candidate_b) = match (
+				load_candidate_entry(a, &candidate_hash)?,
+				load_candidate_entry(b, &candidate_hash)?,
+			) {
+				(None, None) => continue,
+				(Some(_), None) => {
+					if first_visit {
+						differences.push(StateDifference::MissingCandidate { candidate_hash, in_a: true });
+					}
+					continue
+				}
+				(None, Some(_)) => {
+					if first_visit {
+						differences.push(StateDifference::MissingCandidate { candidate_hash, in_a: false });
+					}
+					continue
+				}
+				(Some(candidate_a), Some(candidate_b)) => (candidate_a, candidate_b),
+			};
+
+			if first_visit && candidate_a.approvals != candidate_b.approvals {
+				let approved_a: BTreeSet<_> = candidate_a.approved_validators().collect();
+				let approved_b: BTreeSet<_> = candidate_b.approved_validators().collect();
+
+				differences.push(StateDifference::Approvals {
+					candidate_hash,
+					only_in_a: approved_a.difference(&approved_b).cloned().collect(),
+					only_in_b: approved_b.difference(&approved_a).cloned().collect(),
+				});
+			}
+
+			let (entry_a, entry_b) = match (
+				candidate_a.block_assignments.get(&block_hash),
+				candidate_b.block_assignments.get(&block_hash),
+			) {
+				(None, None) => continue,
+				(Some(_), None) => {
+					differences.push(StateDifference::MissingApprovalEntry {
+						candidate_hash,
+						block_hash,
+						in_a: true,
+					});
+					continue
+				}
+				(None, Some(_)) => {
+					differences.push(StateDifference::MissingApprovalEntry {
+						candidate_hash,
+						block_hash,
+						in_a: false,
+					});
+					continue
+				}
+				(Some(entry_a), Some(entry_b)) => (entry_a, entry_b),
+			};
+
+			let assignments = |entry: &ApprovalEntry| entry.tranches.iter()
+				.flat_map(|t| t.assignments.iter().map(move |&(v, _)| (t.tranche, v)))
+				.collect::<BTreeSet<_>>();
+
+			let assignments_a = assignments(entry_a);
+			let assignments_b = assignments(entry_b);
+
+			if assignments_a != assignments_b {
+				differences.push(StateDifference::Assignments {
+					candidate_hash,
+					block_hash,
+					only_in_a: assignments_a.difference(&assignments_b).cloned().collect(),
+					only_in_b: assignments_b.difference(&assignments_a).cloned().collect(),
+				});
+			} else if entry_a != entry_b {
+				differences.push(StateDifference::ApprovalEntry { candidate_hash, block_hash });
+			}
+		}
+	}
+
+	Ok(differences)
+}
+
+/// Load the hashes of all candidates referenced by the block entry which have
+/// no corresponding candidate entry in the aux store.
+///
+/// This should never return anything except for a corrupt DB, but callers
+/// iterating the candidates of a block should skip and report these rather than
+/// failing.
+pub(crate) fn load_dangling_candidates(store: &impl AuxStore, block_entry: &BlockEntry)
+	-> sp_blockchain::Result<Vec<CandidateHash>>
+{
+	let mut dangling = Vec::new();
+	for &(_, ref candidate_hash) in &block_entry.candidates {
+		if store.get_aux(&candidate_entry_key(candidate_hash))?.is_none() {
+			dangling.push(*candidate_hash);
+		}
+	}
+
+	Ok(dangling)
+}
+
+/// Load the candidate entries for a batch of candidates from the aux store.
+///
+/// The returned vector has the same length and order as `candidate_hashes`.
+pub(crate) fn load_candidate_entries(store: &impl AuxStore, candidate_hashes: &[CandidateHash])
+	-> sp_blockchain::Result<Vec<Option<CandidateEntry>>>
+{
+	// `AuxStore` has no multi-get, so this is a loop for the time being.
+	candidate_hashes.iter().map(|h| load_candidate_entry(store, h)).collect()
+}
+
 /// The key a given block entry is stored under.
 fn block_entry_key(block_hash: &Hash) -> [u8; 46] {
 	const BLOCK_ENTRY_PREFIX: [u8; 14] = *b"Approvals_blck";
@@ -519,6 +1210,17 @@ fn candidate_entry_key(candidate_hash: &CandidateHash) -> [u8; 46] {
 	key
 }
 
+/// The key the full receipt of a given candidate is stored under.
+fn candidate_receipt_key(candidate_hash: &CandidateHash) -> [u8; 46] {
+	const CANDIDATE_RECEIPT_PREFIX: [u8; 14] = *b"Approvals_rcpt";
+
+	let mut key = [0u8; 14 + 32];
+	key[0..14].copy_from_slice(&CANDIDATE_RECEIPT_PREFIX);
+	key[14..][..32].copy_from_slice(candidate_hash.0.as_ref());
+
+	key
+}
+
 /// The key a set of block hashes corresponding to a block number is stored under.
 fn blocks_at_height_key(block_number: BlockNumber) -> [u8; 16] {
 	const BLOCKS_AT_HEIGHT_PREFIX: [u8; 12] = *b"Approvals_at";
@@ -84,10 +84,12 @@ fn make_bitvec(len: usize) -> BitVec<BitOrderLsb0, u8> {
 
 fn make_block_entry(
 	block_hash: Hash,
+	parent_hash: Hash,
 	candidates: Vec<(CoreIndex, CandidateHash)>,
 ) -> BlockEntry {
 	BlockEntry {
 		block_hash,
+		parent_hash,
 		session: 1,
 		slot: 1.into(),
 		relay_vrf_story: RelayVRF([0u8; 32]),
@@ -119,11 +121,12 @@ fn read_write() {
 
 	let block_entry = make_block_entry(
 		hash_a,
+		Hash::repeat_byte(0),
 		vec![(CoreIndex(0), candidate_hash)],
 	);
 
 	let candidate_entry = CandidateEntry {
-		candidate: Default::default(),
+		summary: Default::default(),
 		session: 5,
 		block_assignments: vec![
 			(hash_a, ApprovalEntry {
@@ -164,6 +167,44 @@ fn read_write() {
 	assert!(load_candidate_entry(&store, &candidate_hash).unwrap().is_none());
 }
 
+#[test]
+fn validator_index_encoding_is_stable() {
+	// Validator indices are persisted as little-endian `u32`s inside tranche entries
+	// and as bit positions within bitfields. These must not change with the
+	// in-memory representation of `ValidatorIndex`, or existing data would be
+	// misinterpreted.
+	let tranche_entry = TrancheEntry {
+		tranche: 1,
+		assignments: vec![(2, 3), (258, 4)],
+	};
+
+	assert_eq!(
+		tranche_entry.encode(),
+		vec![
+			1, 0, 0, 0, // tranche
+			8, // compact length: 2
+			2, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, // validator 2 at tick 3
+			2, 1, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, // validator 258 at tick 4
+		],
+	);
+
+	let mut approvals = make_bitvec(10);
+	approvals.set(0, true);
+	approvals.set(9, true);
+
+	assert_eq!(
+		approvals.encode(),
+		vec![
+			40, // compact length: 10 bits
+			0b0000_0001,
+			0b0000_0010,
+		],
+	);
+
+	assert_eq!(TrancheEntry::decode(&mut &tranche_entry.encode()[..]).unwrap(), tranche_entry);
+	assert_eq!(BitVec::<BitOrderLsb0, u8>::decode(&mut &approvals.encode()[..]).unwrap(), approvals);
+}
+
 #[test]
 fn add_block_entry_works() {
 	let store = TestStore::default();
@@ -177,11 +218,13 @@ fn add_block_entry_works() {
 
 	let block_entry_a = make_block_entry(
 		block_hash_a,
+		parent_hash,
 		vec![(CoreIndex(0), candidate_hash_a)],
 	);
 
 	let block_entry_b = make_block_entry(
 		block_hash_b,
+		parent_hash,
 		vec![(CoreIndex(0), candidate_hash_a), (CoreIndex(1), candidate_hash_b)],
 	);
 
@@ -197,7 +240,6 @@ fn add_block_entry_works() {
 
 	add_block_entry(
 		&store,
-		parent_hash,
 		block_number,
 		block_entry_a.clone(),
 		n_validators,
@@ -212,7 +254,6 @@ fn add_block_entry_works() {
 
 	add_block_entry(
 		&store,
-		parent_hash,
 		block_number,
 		block_entry_b.clone(),
 		n_validators,
@@ -239,11 +280,13 @@ fn add_block_entry_adds_child() {
 
 	let mut block_entry_a = make_block_entry(
 		block_hash_a,
+		parent_hash,
 		Vec::new(),
 	);
 
 	let block_entry_b = make_block_entry(
 		block_hash_b,
+		block_hash_a,
 		Vec::new(),
 	);
 
@@ -251,7 +294,6 @@ fn add_block_entry_adds_child() {
 
 	add_block_entry(
 		&store,
-		parent_hash,
 		1,
 		block_entry_a.clone(),
 		n_validators,
@@ -260,7 +302,6 @@ fn add_block_entry_adds_child() {
 
 	add_block_entry(
 		&store,
-		block_hash_a,
 		2,
 		block_entry_b.clone(),
 		n_validators,
@@ -273,6 +314,291 @@ fn add_block_entry_adds_child() {
 	assert_eq!(load_block_entry(&store, &block_hash_b).unwrap(), Some(block_entry_b));
 }
 
+#[test]
+fn children_are_independent_of_import_order() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let parent_hash = Hash::repeat_byte(1);
+	let block_hash = Hash::repeat_byte(2);
+	let child_hash_1 = Hash::repeat_byte(4);
+	let child_hash_2 = Hash::repeat_byte(3);
+
+	let block_entry = make_block_entry(block_hash, parent_hash, Vec::new());
+	let child_entry_1 = make_block_entry(child_hash_1, block_hash, Vec::new());
+	let child_entry_2 = make_block_entry(child_hash_2, block_hash, Vec::new());
+
+	let n_validators = 10;
+
+	for &(store, ref children) in &[
+		(&store_a, vec![child_entry_1.clone(), child_entry_2.clone()]),
+		(&store_b, vec![child_entry_2.clone(), child_entry_1.clone()]),
+	] {
+		add_block_entry(store, 1, block_entry.clone(), n_validators, |_| None).unwrap();
+
+		for child in children {
+			add_block_entry(store, 2, child.clone(), n_validators, |_| None).unwrap();
+		}
+	}
+
+	let entry_a = load_block_entry(&store_a, &block_hash).unwrap().unwrap();
+	let entry_b = load_block_entry(&store_b, &block_hash).unwrap().unwrap();
+	assert_eq!(entry_a.children, vec![child_hash_2, child_hash_1]);
+	assert_eq!(entry_a, entry_b);
+
+	assert_eq!(
+		state_hash(&store_a, &block_hash).unwrap(),
+		state_hash(&store_b, &block_hash).unwrap(),
+	);
+}
+
+#[test]
+fn add_block_entry_normalizes_approved_bitfield() {
+	let store = TestStore::default();
+
+	let parent_hash = Hash::repeat_byte(1);
+	let block_hash = Hash::repeat_byte(2);
+
+	let candidate_hash_a = CandidateHash(Hash::repeat_byte(3));
+	let candidate_hash_b = CandidateHash(Hash::repeat_byte(4));
+
+	let mut block_entry = make_block_entry(
+		block_hash,
+		parent_hash,
+		vec![(CoreIndex(0), candidate_hash_a), (CoreIndex(1), candidate_hash_b)],
+	);
+
+	block_entry.approved_bitfield = make_bitvec(1);
+
+	let n_validators = 10;
+
+	let mut new_candidate_info = HashMap::new();
+	new_candidate_info.insert(candidate_hash_a, NewCandidateInfo {
+		candidate: make_candidate(1.into(), parent_hash),
+		backing_group: GroupIndex(0),
+		our_assignment: None,
+	});
+
+	new_candidate_info.insert(candidate_hash_b, NewCandidateInfo {
+		candidate: make_candidate(2.into(), parent_hash),
+		backing_group: GroupIndex(1),
+		our_assignment: None,
+	});
+
+	add_block_entry(
+		&store,
+		1,
+		block_entry,
+		n_validators,
+		|h| new_candidate_info.get(h).map(|x| x.clone()),
+	).unwrap();
+
+	let loaded = load_block_entry(&store, &block_hash).unwrap().unwrap();
+	assert_eq!(loaded.candidates.len(), 2);
+	assert_eq!(loaded.approved_bitfield, make_bitvec(2));
+}
+
+#[test]
+fn descendants_follow_children() {
+	let store = TestStore::default();
+
+	//   -> B1 -> C1
+	// A -> B2 -> C2 -> D2
+	//         -> C3
+	//
+	// Finalizing B1 means B2 and all its descendants are pruned.
+
+	let genesis = Hash::repeat_byte(0);
+
+	let block_hash_a = Hash::repeat_byte(1);
+	let block_hash_b1 = Hash::repeat_byte(2);
+	let block_hash_b2 = Hash::repeat_byte(3);
+	let block_hash_c1 = Hash::repeat_byte(4);
+	let block_hash_c2 = Hash::repeat_byte(5);
+	let block_hash_c3 = Hash::repeat_byte(6);
+	let block_hash_d2 = Hash::repeat_byte(7);
+
+	let blocks = vec![
+		(1, make_block_entry(block_hash_a, genesis, Vec::new())),
+		(2, make_block_entry(block_hash_b1, block_hash_a, Vec::new())),
+		(2, make_block_entry(block_hash_b2, block_hash_a, Vec::new())),
+		(3, make_block_entry(block_hash_c1, block_hash_b1, Vec::new())),
+		(3, make_block_entry(block_hash_c2, block_hash_b2, Vec::new())),
+		(3, make_block_entry(block_hash_c3, block_hash_b2, Vec::new())),
+		(4, make_block_entry(block_hash_d2, block_hash_c2, Vec::new())),
+	];
+
+	for (number, block_entry) in blocks {
+		add_block_entry(
+			&store,
+			number,
+			block_entry,
+			10,
+			|_| None,
+		).unwrap();
+	}
+
+	assert_eq!(
+		load_block_entry(&store, &block_hash_c2).unwrap().unwrap().parent_hash,
+		block_hash_b2,
+	);
+
+	assert_eq!(
+		descendants(&store, &block_hash_b2).unwrap(),
+		vec![block_hash_c2, block_hash_c3, block_hash_d2],
+	);
+
+	assert_eq!(descendants(&store, &block_hash_b1).unwrap(), vec![block_hash_c1]);
+	assert_eq!(descendants(&store, &block_hash_a).unwrap().len(), 6);
+	assert!(descendants(&store, &block_hash_d2).unwrap().is_empty());
+	assert!(descendants(&store, &Hash::repeat_byte(8)).unwrap().is_empty());
+
+	let pruned_fork = descendants(&store, &block_hash_b2).unwrap();
+
+	canonicalize(&store, 2, block_hash_b1).unwrap();
+
+	assert!(load_block_entry(&store, &block_hash_b2).unwrap().is_none());
+	for block_hash in pruned_fork {
+		assert!(load_block_entry(&store, &block_hash).unwrap().is_none());
+	}
+
+	assert!(load_block_entry(&store, &block_hash_c1).unwrap().is_some());
+	assert_eq!(load_blocks_at_height(&store, 3).unwrap(), vec![block_hash_c1]);
+	assert!(load_blocks_at_height(&store, 4).unwrap().is_empty());
+}
+
+#[test]
+fn recover_approved_bitfields_works() {
+	let store = TestStore::default();
+
+	let parent_hash = Hash::repeat_byte(1);
+	let block_hash_a = Hash::repeat_byte(2);
+	let block_hash_b = Hash::repeat_byte(3);
+
+	let candidate_hash_a = CandidateHash(Hash::repeat_byte(4));
+	let candidate_hash_b = CandidateHash(Hash::repeat_byte(5));
+
+	let block_entry_a = make_block_entry(
+		block_hash_a,
+		parent_hash,
+		vec![(CoreIndex(0), candidate_hash_a), (CoreIndex(1), candidate_hash_b)],
+	);
+
+	let block_entry_b = make_block_entry(
+		block_hash_b,
+		block_hash_a,
+		vec![(CoreIndex(0), candidate_hash_b)],
+	);
+
+	let n_validators = 10;
+
+	let mut new_candidate_info = HashMap::new();
+	new_candidate_info.insert(candidate_hash_a, NewCandidateInfo {
+		candidate: make_candidate(1.into(), parent_hash),
+		backing_group: GroupIndex(0),
+		our_assignment: None,
+	});
+
+	new_candidate_info.insert(candidate_hash_b, NewCandidateInfo {
+		candidate: make_candidate(2.into(), parent_hash),
+		backing_group: GroupIndex(1),
+		our_assignment: None,
+	});
+
+	add_block_entry(
+		&store,
+		1,
+		block_entry_a.clone(),
+		n_validators,
+		|h| new_candidate_info.get(h).map(|x| x.clone()),
+	).unwrap();
+
+	add_block_entry(
+		&store,
+		2,
+		block_entry_b.clone(),
+		n_validators,
+		|h| new_candidate_info.get(h).map(|x| x.clone()),
+	).unwrap();
+
+	assert!(recover_approved_bitfields(&store).unwrap().is_empty());
+
+	// Candidate A is approved under block A, but the bitfield doesn't reflect that.
+	let mut candidate_entry_a = load_candidate_entry(&store, &candidate_hash_a).unwrap().unwrap();
+	candidate_entry_a.block_assignments.get_mut(&block_hash_a).unwrap().approved = true;
+	store.write_candidate_entry(&candidate_hash_a, &candidate_entry_a);
+
+	// The bitfield of block B claims candidate B is approved, but it isn't.
+	let mut corrupt_block_entry_b = block_entry_b.clone();
+	corrupt_block_entry_b.approved_bitfield.set(0, true);
+	store.write_block_entry(&block_hash_b, &corrupt_block_entry_b);
+
+	assert_eq!(
+		recover_approved_bitfields(&store).unwrap(),
+		vec![block_hash_a, block_hash_b],
+	);
+
+	let mut expected_a = make_bitvec(2);
+	expected_a.set(0, true);
+
+	assert_eq!(load_block_entry(&store, &block_hash_a).unwrap().unwrap().approved_bitfield, expected_a);
+	assert_eq!(load_block_entry(&store, &block_hash_b).unwrap().unwrap().approved_bitfield, make_bitvec(1));
+
+	assert!(recover_approved_bitfields(&store).unwrap().is_empty());
+}
+
+#[test]
+fn candidate_receipt_is_stored_separately() {
+	let store = TestStore::default();
+
+	let parent_hash = Hash::repeat_byte(1);
+	let block_hash = Hash::repeat_byte(2);
+	let candidate_hash = CandidateHash(Hash::repeat_byte(3));
+
+	let block_entry = make_block_entry(
+		block_hash,
+		parent_hash,
+		vec![(CoreIndex(0), candidate_hash)],
+	);
+
+	let receipt = make_candidate(1.into(), parent_hash);
+
+	let mut new_candidate_info = HashMap::new();
+	new_candidate_info.insert(candidate_hash, NewCandidateInfo {
+		candidate: receipt.clone(),
+		backing_group: GroupIndex(0),
+		our_assignment: None,
+	});
+
+	add_block_entry(
+		&store,
+		1,
+		block_entry,
+		10,
+		|h| new_candidate_info.get(h).map(|x| x.clone()),
+	).unwrap();
+
+	let candidate_entry = load_candidate_entry(&store, &candidate_hash).unwrap().unwrap();
+	assert_eq!(candidate_entry.summary, CandidateSummary {
+		hash: candidate_hash,
+		para_id: 1.into(),
+		relay_parent: parent_hash,
+	});
+
+	// The stored candidate entry is smaller than the receipt alone.
+	let stored_entry = store.get_aux(&candidate_entry_key(&candidate_hash)).unwrap().unwrap();
+	assert!(stored_entry.len() < receipt.encoded_size());
+
+	assert_eq!(candidate_entry.receipt(&store).unwrap(), Some(receipt));
+
+	// The receipt goes away with the candidate entry.
+	canonicalize(&store, 2, Hash::repeat_byte(4)).unwrap();
+
+	assert!(load_candidate_entry(&store, &candidate_hash).unwrap().is_none());
+	assert!(candidate_entry.receipt(&store).unwrap().is_none());
+	assert!(store.get_aux(&candidate_receipt_key(&candidate_hash)).unwrap().is_none());
+}
+
 #[test]
 fn clear_works() {
 	let store = TestStore::default();
@@ -286,11 +612,12 @@ fn clear_works() {
 
 	let block_entry = make_block_entry(
 		hash_a,
+		Hash::repeat_byte(0),
 		vec![(CoreIndex(0), candidate_hash)],
 	);
 
 	let candidate_entry = CandidateEntry {
-		candidate: Default::default(),
+		summary: Default::default(),
 		session: 5,
 		block_assignments: vec![
 			(hash_a, ApprovalEntry {
@@ -315,8 +642,15 @@ fn clear_works() {
 	assert_eq!(load_block_entry(&store, &hash_a).unwrap(), Some(block_entry));
 	assert_eq!(load_candidate_entry(&store, &candidate_hash).unwrap(), Some(candidate_entry));
 
+	store.inner.borrow_mut().insert(
+		candidate_receipt_key(&candidate_hash).to_vec(),
+		CandidateReceipt::default().encode(),
+	);
+
 	clear(&store).unwrap();
 
+	assert!(store.get_aux(&candidate_receipt_key(&candidate_hash)).unwrap().is_none());
+
 	assert!(load_stored_blocks(&store).unwrap().is_none());
 	assert!(load_blocks_at_height(&store, 1).unwrap().is_empty());
 	assert!(load_block_entry(&store, &hash_a).unwrap().is_none());
@@ -325,55 +659,892 @@ fn clear_works() {
 
 
 #[test]
-fn canonicalize_works() {
+fn load_candidate_with_approval_works() {
 	let store = TestStore::default();
 
-	//   -> B1 -> C1 -> D1
-	// A -> B2 -> C2 -> D2
-	//
-	// We'll canonicalize C1. Everything except D1 should disappear.
-	//
-	// Candidates:
-	// Cand1 in B2
-	// Cand2 in C2
-	// Cand3 in C2 and D1
-	// Cand4 in D1
-	// Cand5 in D2
-	// Only Cand3 and Cand4 should remain after canonicalize.
+	let block_hash_a = Hash::repeat_byte(1);
+	let block_hash_b = Hash::repeat_byte(2);
+	let candidate_hash = CandidateHash(Hash::repeat_byte(3));
 
-	let n_validators = 10;
+	let approval_entry = ApprovalEntry {
+		tranches: Vec::new(),
+		backing_group: GroupIndex(1),
+		next_wakeup: 1000,
+		our_assignment: None,
+		assignments: Default::default(),
+		approved: false,
+	};
 
-	store.write_stored_blocks(StoredBlockRange(1, 5));
+	let candidate_entry = CandidateEntry {
+		summary: Default::default(),
+		session: 5,
+		block_assignments: vec![
+			(block_hash_a, approval_entry.clone()),
+		].into_iter().collect(),
+		approvals: Default::default(),
+	};
 
-	let genesis = Hash::repeat_byte(0);
+	assert!(load_candidate_with_approval(&store, &block_hash_a, &candidate_hash).unwrap().is_none());
 
-	let block_hash_a = Hash::repeat_byte(1);
-	let block_hash_b1 = Hash::repeat_byte(2);
-	let block_hash_b2 = Hash::repeat_byte(3);
-	let block_hash_c1 = Hash::repeat_byte(4);
-	let block_hash_c2 = Hash::repeat_byte(5);
-	let block_hash_d1 = Hash::repeat_byte(6);
-	let block_hash_d2 = Hash::repeat_byte(7);
+	store.write_candidate_entry(&candidate_hash, &candidate_entry);
 
-	let cand_hash_1 = CandidateHash(Hash::repeat_byte(10));
-	let cand_hash_2 = CandidateHash(Hash::repeat_byte(11));
-	let cand_hash_3 = CandidateHash(Hash::repeat_byte(12));
-	let cand_hash_4 = CandidateHash(Hash::repeat_byte(13));
-	let cand_hash_5 = CandidateHash(Hash::repeat_byte(15));
+	assert_eq!(
+		load_candidate_with_approval(&store, &block_hash_a, &candidate_hash).unwrap(),
+		Some((candidate_entry, approval_entry)),
+	);
 
-	let block_entry_a = make_block_entry(block_hash_a, Vec::new());
-	let block_entry_b1 = make_block_entry(block_hash_b1, Vec::new());
-	let block_entry_b2 = make_block_entry(block_hash_b2, vec![(CoreIndex(0), cand_hash_1)]);
-	let block_entry_c1 = make_block_entry(block_hash_c1, Vec::new());
-	let block_entry_c2 = make_block_entry(
-		block_hash_c2,
-		vec![(CoreIndex(0), cand_hash_2), (CoreIndex(1), cand_hash_3)],
+	assert!(load_candidate_with_approval(&store, &block_hash_b, &candidate_hash).unwrap().is_none());
+}
+
+#[test]
+fn load_candidate_entries_preserves_order() {
+	let store = TestStore::default();
+
+	let candidate_hash_a = CandidateHash(Hash::repeat_byte(1));
+	let candidate_hash_b = CandidateHash(Hash::repeat_byte(2));
+	let candidate_hash_c = CandidateHash(Hash::repeat_byte(3));
+
+	let make_candidate_entry = |session| CandidateEntry {
+		summary: Default::default(),
+		session,
+		block_assignments: BTreeMap::new(),
+		approvals: Default::default(),
+	};
+
+	store.write_candidate_entry(&candidate_hash_a, &make_candidate_entry(1));
+	store.write_candidate_entry(&candidate_hash_c, &make_candidate_entry(3));
+
+	assert!(load_candidate_entries(&store, &[]).unwrap().is_empty());
+
+	assert_eq!(
+		load_candidate_entries(
+			&store,
+			&[candidate_hash_c, candidate_hash_b, candidate_hash_a],
+		).unwrap(),
+		vec![Some(make_candidate_entry(3)), None, Some(make_candidate_entry(1))],
+	);
+}
+
+#[test]
+fn approving_validators_count_is_union() {
+	let store = TestStore::default();
+
+	let block_hash = Hash::repeat_byte(1);
+	let candidate_hash_a = CandidateHash(Hash::repeat_byte(2));
+	let candidate_hash_b = CandidateHash(Hash::repeat_byte(3));
+
+	let block_entry = make_block_entry(
+		block_hash,
+		Hash::repeat_byte(0),
+		vec![(CoreIndex(0), candidate_hash_a), (CoreIndex(1), candidate_hash_b)],
+	);
+
+	let make_candidate_entry = |approved: &[usize]| {
+		let mut approvals = make_bitvec(10);
+		for &i in approved {
+			approvals.set(i, true);
+		}
+
+		CandidateEntry {
+			summary: Default::default(),
+			session: 1,
+			block_assignments: BTreeMap::new(),
+			approvals,
+		}
+	};
+
+	assert_eq!(load_approving_validators_count(&store, &block_entry).unwrap(), 0);
+
+	store.write_candidate_entry(&candidate_hash_a, &make_candidate_entry(&[0, 1, 2]));
+	assert_eq!(load_approving_validators_count(&store, &block_entry).unwrap(), 3);
+
+	store.write_candidate_entry(&candidate_hash_b, &make_candidate_entry(&[2, 3]));
+	assert_eq!(load_approving_validators_count(&store, &block_entry).unwrap(), 4);
+}
+
+#[test]
+fn dangling_candidate_reference_is_reported() {
+	let store = TestStore::default();
+
+	let block_hash = Hash::repeat_byte(1);
+	let candidate_hash_a = CandidateHash(Hash::repeat_byte(2));
+	let candidate_hash_b = CandidateHash(Hash::repeat_byte(3));
+
+	let block_entry = make_block_entry(
+		block_hash,
+		Hash::repeat_byte(0),
+		vec![(CoreIndex(0), candidate_hash_a), (CoreIndex(1), candidate_hash_b)],
+	);
+
+	let candidate_entry = CandidateEntry {
+		summary: Default::default(),
+		session: 1,
+		block_assignments: vec![
+			(block_hash, ApprovalEntry {
+				tranches: Vec::new(),
+				backing_group: GroupIndex(1),
+				next_wakeup: 1000,
+				our_assignment: None,
+				assignments: Default::default(),
+				approved: false,
+			})
+		].into_iter().collect(),
+		approvals: Default::default(),
+	};
+
+	store.write_stored_blocks(StoredBlockRange(1, 3));
+	store.write_blocks_at_height(1, &[block_hash]);
+	store.write_block_entry(&block_hash, &block_entry);
+	store.write_candidate_entry(&candidate_hash_a, &candidate_entry);
+
+	assert_eq!(
+		load_dangling_candidates(&store, &block_entry).unwrap(),
+		vec![candidate_hash_b],
+	);
+
+	// pruning is unaffected by the dangling reference.
+	canonicalize(&store, 2, Hash::repeat_byte(4)).unwrap();
+
+	assert!(load_block_entry(&store, &block_hash).unwrap().is_none());
+	assert!(load_candidate_entry(&store, &candidate_hash_a).unwrap().is_none());
+	assert!(load_candidate_entry(&store, &candidate_hash_b).unwrap().is_none());
+}
+
+#[test]
+fn overlay_is_speculative_until_committed() {
+	let store = TestStore::default();
+	let direct_store = TestStore::default();
+
+	let parent_hash = Hash::repeat_byte(1);
+	let block_hash = Hash::repeat_byte(2);
+	let candidate_hash = CandidateHash(Hash::repeat_byte(3));
+
+	let block_entry = make_block_entry(
+		block_hash,
+		parent_hash,
+		vec![(CoreIndex(0), candidate_hash)],
+	);
+
+	let n_validators = 10;
+
+	let mut new_candidate_info = HashMap::new();
+	new_candidate_info.insert(candidate_hash, NewCandidateInfo {
+		candidate: make_candidate(1.into(), parent_hash),
+		backing_group: GroupIndex(0),
+		our_assignment: None,
+	});
+
+	// Speculative import, then drop.
+	{
+		let overlay = OverlayedBackend::new(&store);
+		add_block_entry(
+			&overlay,
+			1,
+			block_entry.clone(),
+			n_validators,
+			|h| new_candidate_info.get(h).map(|x| x.clone()),
+		).unwrap();
+
+		assert!(!overlay.is_empty());
+		assert_eq!(load_block_entry(&overlay, &block_hash).unwrap(), Some(block_entry.clone()));
+		assert!(load_candidate_entry(&overlay, &candidate_hash).unwrap().is_some());
+	}
+
+	assert!(store.inner.borrow().is_empty());
+
+	// Import through an overlay and commit.
+	{
+		let overlay = OverlayedBackend::new(&store);
+		add_block_entry(
+			&overlay,
+			1,
+			block_entry.clone(),
+			n_validators,
+			|h| new_candidate_info.get(h).map(|x| x.clone()),
+		).unwrap();
+
+		overlay.commit().unwrap();
+	}
+
+	add_block_entry(
+		&direct_store,
+		1,
+		block_entry.clone(),
+		n_validators,
+		|h| new_candidate_info.get(h).map(|x| x.clone()),
+	).unwrap();
+
+	assert_eq!(*store.inner.borrow(), *direct_store.inner.borrow());
+
+	// Deletions are buffered as well.
+	{
+		let overlay = OverlayedBackend::new(&store);
+		clear(&overlay).unwrap();
+
+		assert!(load_block_entry(&overlay, &block_hash).unwrap().is_none());
+		assert_eq!(load_block_entry(&store, &block_hash).unwrap(), Some(block_entry));
+	}
+}
+
+#[test]
+fn candidate_entry_approved_validators() {
+	let mut candidate_entry = CandidateEntry {
+		summary: Default::default(),
+		session: 1,
+		block_assignments: BTreeMap::new(),
+		approvals: make_bitvec(10),
+	};
+
+	assert_eq!(candidate_entry.approved_validators().count(), 0);
+
+	candidate_entry.approvals.set(7, true);
+	candidate_entry.approvals.set(1, true);
+	candidate_entry.approvals.set(4, true);
+
+	assert_eq!(candidate_entry.approved_validators().collect::<Vec<_>>(), vec![1, 4, 7]);
+}
+
+#[test]
+fn candidate_entry_approving_set() {
+	let mut approval_entry = ApprovalEntry {
+		tranches: Vec::new(),
+		backing_group: GroupIndex(0),
+		next_wakeup: 0,
+		our_assignment: None,
+		assignments: make_bitvec(10),
+		approved: false,
+	};
+
+	let mut candidate_entry = CandidateEntry {
+		summary: Default::default(),
+		session: 1,
+		block_assignments: BTreeMap::new(),
+		approvals: make_bitvec(10),
+	};
+
+	for i in 0..8 {
+		approval_entry.import_assignment(0, i, 0);
+	}
+
+	for &i in &[0, 1, 3, 4, 6, 7, 9] {
+		candidate_entry.approvals.set(i, true);
+	}
+
+	// Validator 9 approved without an assignment under this block.
+	assert_eq!(
+		candidate_entry.approving_set(&approval_entry),
+		vec![0, 1, 3, 4, 6, 7],
+	);
+}
+
+#[test]
+fn candidate_entry_block_pruning() {
+	let block_hash_a = Hash::repeat_byte(1);
+	let block_hash_b = Hash::repeat_byte(2);
+	let block_hash_c = Hash::repeat_byte(3);
+
+	let approval_entry = ApprovalEntry {
+		tranches: Vec::new(),
+		backing_group: GroupIndex(1),
+		next_wakeup: 1000,
+		our_assignment: None,
+		assignments: Default::default(),
+		approved: false,
+	};
+
+	let candidate_entry = CandidateEntry {
+		summary: Default::default(),
+		session: 5,
+		block_assignments: vec![
+			(block_hash_a, approval_entry.clone()),
+			(block_hash_b, approval_entry.clone()),
+			(block_hash_c, approval_entry),
+		].into_iter().collect(),
+		approvals: Default::default(),
+	};
+
+	assert_eq!(
+		candidate_entry.blocks().collect::<Vec<_>>(),
+		vec![&block_hash_a, &block_hash_b, &block_hash_c],
+	);
+
+	{
+		let mut candidate_entry = candidate_entry.clone();
+		assert!(!candidate_entry.remove_block(&block_hash_b));
+		assert!(!candidate_entry.remove_block(&block_hash_b));
+		assert!(!candidate_entry.remove_block(&block_hash_a));
+		assert!(candidate_entry.remove_block(&block_hash_c));
+		assert_eq!(candidate_entry.blocks().count(), 0);
+		assert!(candidate_entry.is_empty());
+	}
+
+	{
+		let mut candidate_entry = candidate_entry.clone();
+		candidate_entry.retain_blocks(|_| true);
+		assert_eq!(candidate_entry.blocks().count(), 3);
+		assert!(!candidate_entry.is_empty());
+
+		candidate_entry.retain_blocks(|h| h != &block_hash_b);
+		assert_eq!(
+			candidate_entry.blocks().collect::<Vec<_>>(),
+			vec![&block_hash_a, &block_hash_c],
+		);
+
+		candidate_entry.retain_blocks(|_| false);
+		assert!(candidate_entry.is_empty());
+	}
+}
+
+#[test]
+fn candidate_entry_approvals_summary() {
+	let mut candidate_entry = CandidateEntry {
+		summary: Default::default(),
+		session: 1,
+		block_assignments: BTreeMap::new(),
+		approvals: make_bitvec(10),
+	};
+
+	assert_eq!(candidate_entry.approvals_count(), (0, 10));
+
+	candidate_entry.approvals.set(2, true);
+	candidate_entry.approvals.set(9, true);
+
+	assert_eq!(candidate_entry.approvals_count(), (2, 10));
+	assert!(candidate_entry.has_approval(2));
+	assert!(candidate_entry.has_approval(9));
+	assert!(!candidate_entry.has_approval(3));
+	assert!(!candidate_entry.has_approval(10));
+	assert!(!candidate_entry.has_approval(ValidatorIndex::max_value()));
+}
+
+#[test]
+fn import_assignment_is_order_independent() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let block_hash = Hash::repeat_byte(1);
+	let candidate_hash = CandidateHash(Hash::repeat_byte(2));
+
+	let block_entry = make_block_entry(
+		block_hash,
+		Hash::repeat_byte(0),
+		vec![(CoreIndex(0), candidate_hash)],
+	);
+
+	let make_approval_entry = || ApprovalEntry {
+		tranches: Vec::new(),
+		backing_group: GroupIndex(0),
+		next_wakeup: 0,
+		our_assignment: None,
+		assignments: make_bitvec(10),
+		approved: false,
+	};
+
+	let assignments: Vec<(DelayTranche, ValidatorIndex, Tick)> = vec![
+		(2, 7, 20),
+		(0, 3, 10),
+		(0, 1, 12),
+		(1, 9, 15),
+		(0, 5, 11),
+	];
+
+	let mut entry_a = make_approval_entry();
+	for &(tranche, validator, tick) in &assignments {
+		assert!(entry_a.import_assignment(tranche, validator, tick));
+	}
+
+	let mut entry_b = make_approval_entry();
+	for &(tranche, validator, tick) in assignments.iter().rev() {
+		assert!(entry_b.import_assignment(tranche, validator, tick));
+	}
+
+	// duplicates are rejected, keeping the original tick.
+	assert!(!entry_b.import_assignment(0, 3, 100));
+
+	assert_eq!(entry_a.encode(), entry_b.encode());
+	assert_eq!(
+		entry_a.tranches.iter().map(|t| t.tranche).collect::<Vec<_>>(),
+		vec![0, 1, 2],
+	);
+	assert_eq!(entry_a.tranches[0].assignments, vec![(1, 12), (3, 10), (5, 11)]);
+	assert_eq!(entry_a.assignments.count_ones(), 5);
+
+	let make_candidate_entry = |approval_entry| CandidateEntry {
+		summary: Default::default(),
+		session: 1,
+		block_assignments: vec![(block_hash, approval_entry)].into_iter().collect(),
+		approvals: make_bitvec(10),
+	};
+
+	store_a.write_block_entry(&block_hash, &block_entry);
+	store_a.write_candidate_entry(&candidate_hash, &make_candidate_entry(entry_a));
+
+	store_b.write_block_entry(&block_hash, &block_entry);
+	store_b.write_candidate_entry(&candidate_hash, &make_candidate_entry(entry_b));
+
+	let hash_a = state_hash(&store_a, &block_hash).unwrap().unwrap();
+	let hash_b = state_hash(&store_b, &block_hash).unwrap().unwrap();
+	assert_eq!(hash_a, hash_b);
+
+	assert!(state_hash(&store_a, &Hash::repeat_byte(3)).unwrap().is_none());
+
+	let mut different_entry = make_approval_entry();
+	different_entry.import_assignment(0, 1, 12);
+	store_b.write_candidate_entry(&candidate_hash, &make_candidate_entry(different_entry));
+
+	assert_ne!(state_hash(&store_b, &block_hash).unwrap().unwrap(), hash_a);
+}
+
+#[test]
+fn import_assignment_rejects_out_of_bounds_validator() {
+	let mut approval_entry = ApprovalEntry {
+		tranches: Vec::new(),
+		backing_group: GroupIndex(0),
+		next_wakeup: 0,
+		our_assignment: None,
+		assignments: make_bitvec(10),
+		approved: false,
+	};
+
+	let before = approval_entry.clone();
+
+	assert!(!approval_entry.import_assignment(0, 10, 1));
+	assert!(!approval_entry.import_assignment(0, ValidatorIndex::max_value(), 1));
+	assert_eq!(approval_entry, before);
+
+	assert!(approval_entry.import_assignment(0, 9, 1));
+	assert_eq!(approval_entry.tranches[0].assignments, vec![(9, 1)]);
+	assert_eq!(approval_entry.assignments.count_ones(), 1);
+}
+
+#[test]
+fn import_assignment_rejects_validator_in_second_tranche() {
+	let mut approval_entry = ApprovalEntry {
+		tranches: Vec::new(),
+		backing_group: GroupIndex(0),
+		next_wakeup: 0,
+		our_assignment: None,
+		assignments: make_bitvec(10),
+		approved: false,
+	};
+
+	assert!(approval_entry.import_assignment(1, 4, 5));
+
+	let before = approval_entry.clone();
+	assert!(!approval_entry.import_assignment(0, 4, 2));
+	assert!(!approval_entry.import_assignment(2, 4, 8));
+	assert_eq!(approval_entry, before);
+
+	assert_eq!(
+		approval_entry.no_show_validators(&make_bitvec(10), 100, 10),
+		vec![4],
+	);
+}
+
+#[test]
+fn approval_entry_assignments_with_status() {
+	let no_show_duration = 10;
+
+	let approval_entry = ApprovalEntry {
+		tranches: vec![
+			TrancheEntry {
+				tranche: 0,
+				assignments: vec![(0, 5), (1, 5)],
+			},
+			TrancheEntry {
+				tranche: 2,
+				assignments: vec![(2, 8)],
+			},
+		],
+		backing_group: GroupIndex(0),
+		next_wakeup: 0,
+		our_assignment: None,
+		assignments: make_bitvec(4),
+		approved: false,
+	};
+
+	let mut approvals = make_bitvec(4);
+	approvals.set(1, true);
+
+	let statuses = |tick_now| approval_entry.assignments_with_status(
+		&approvals,
+		tick_now,
+		no_show_duration,
+	).collect::<Vec<_>>();
+
+	// Just before the deadline of the tranche 0 assignments.
+	assert_eq!(statuses(14), vec![
+		(0, 0, AssignmentStatus::Pending { deadline: 15 }),
+		(1, 0, AssignmentStatus::Approved),
+		(2, 2, AssignmentStatus::Pending { deadline: 18 }),
+	]);
+
+	// Exactly at the deadline.
+	assert_eq!(statuses(15), vec![
+		(0, 0, AssignmentStatus::NoShow),
+		(1, 0, AssignmentStatus::Approved),
+		(2, 2, AssignmentStatus::Pending { deadline: 18 }),
+	]);
+
+	assert_eq!(statuses(18), vec![
+		(0, 0, AssignmentStatus::NoShow),
+		(1, 0, AssignmentStatus::Approved),
+		(2, 2, AssignmentStatus::NoShow),
+	]);
+}
+
+#[test]
+fn diff_pinpoints_approval_bit() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let parent_hash = Hash::repeat_byte(1);
+	let block_hash = Hash::repeat_byte(2);
+	let candidate_hash_a = CandidateHash(Hash::repeat_byte(3));
+	let candidate_hash_b = CandidateHash(Hash::repeat_byte(4));
+
+	let block_entry = make_block_entry(
+		block_hash,
+		parent_hash,
+		vec![(CoreIndex(0), candidate_hash_a), (CoreIndex(1), candidate_hash_b)],
+	);
+
+	let n_validators = 10;
+
+	let mut new_candidate_info = HashMap::new();
+	new_candidate_info.insert(candidate_hash_a, NewCandidateInfo {
+		candidate: make_candidate(1.into(), parent_hash),
+		backing_group: GroupIndex(0),
+		our_assignment: None,
+	});
+
+	new_candidate_info.insert(candidate_hash_b, NewCandidateInfo {
+		candidate: make_candidate(2.into(), parent_hash),
+		backing_group: GroupIndex(1),
+		our_assignment: None,
+	});
+
+	for store in &[&store_a, &store_b] {
+		add_block_entry(
+			*store,
+			1,
+			block_entry.clone(),
+			n_validators,
+			|h| new_candidate_info.get(h).map(|x| x.clone()),
+		).unwrap();
+	}
+
+	assert!(diff(&store_a, &store_b).unwrap().is_empty());
+
+	let mut candidate_entry = load_candidate_entry(&store_a, &candidate_hash_b).unwrap().unwrap();
+	candidate_entry.approvals.set(3, true);
+	store_a.write_candidate_entry(&candidate_hash_b, &candidate_entry);
+
+	assert_eq!(
+		diff(&store_a, &store_b).unwrap(),
+		vec![StateDifference::Approvals {
+			candidate_hash: candidate_hash_b,
+			only_in_a: vec![3],
+			only_in_b: vec![],
+		}],
+	);
+
+	assert_eq!(
+		diff(&store_b, &store_a).unwrap(),
+		vec![StateDifference::Approvals {
+			candidate_hash: candidate_hash_b,
+			only_in_a: vec![],
+			only_in_b: vec![3],
+		}],
+	);
+}
+
+// Add the same blocks to both stores, with a fresh candidate entry for every candidate.
+fn add_blocks_to_both(store_a: &TestStore, store_b: &TestStore, blocks: Vec<(BlockNumber, BlockEntry)>) {
+	let n_validators = 10;
+
+	for (number, block_entry) in blocks {
+		let parent_hash = block_entry.parent_hash;
+		for store in &[store_a, store_b] {
+			add_block_entry(
+				*store,
+				number,
+				block_entry.clone(),
+				n_validators,
+				|_| Some(NewCandidateInfo {
+					candidate: make_candidate(1.into(), parent_hash),
+					backing_group: GroupIndex(0),
+					our_assignment: None,
+				}),
+			).unwrap();
+		}
+	}
+}
+
+#[test]
+fn diff_reports_stored_blocks() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let block_hash = Hash::repeat_byte(2);
+
+	add_blocks_to_both(&store_a, &store_b, vec![
+		(1, make_block_entry(block_hash, Hash::repeat_byte(1), Vec::new())),
+	]);
+
+	store_b.write_stored_blocks(StoredBlockRange(1, 3));
+
+	assert_eq!(
+		diff(&store_a, &store_b).unwrap(),
+		vec![StateDifference::StoredBlocks {
+			a: Some(StoredBlockRange(1, 2)),
+			b: Some(StoredBlockRange(1, 3)),
+		}],
+	);
+}
+
+#[test]
+fn diff_reports_missing_block() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let parent_hash = Hash::repeat_byte(1);
+	let block_hash_a = Hash::repeat_byte(2);
+	let block_hash_b = Hash::repeat_byte(3);
+
+	add_blocks_to_both(&store_a, &store_b, vec![
+		(1, make_block_entry(block_hash_a, parent_hash, Vec::new())),
+	]);
+
+	add_block_entry(
+		&store_b,
+		1,
+		make_block_entry(block_hash_b, parent_hash, Vec::new()),
+		10,
+		|_| None,
+	).unwrap();
+
+	assert_eq!(
+		diff(&store_a, &store_b).unwrap(),
+		vec![StateDifference::MissingBlock { block_hash: block_hash_b, in_a: false }],
+	);
+}
+
+#[test]
+fn diff_reports_missing_candidate() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let block_hash = Hash::repeat_byte(2);
+	let candidate_hash = CandidateHash(Hash::repeat_byte(3));
+
+	add_blocks_to_both(&store_a, &store_b, vec![
+		(1, make_block_entry(block_hash, Hash::repeat_byte(1), vec![(CoreIndex(0), candidate_hash)])),
+	]);
+
+	store_b.inner.borrow_mut().remove(&candidate_entry_key(&candidate_hash)[..]);
+
+	assert_eq!(
+		diff(&store_a, &store_b).unwrap(),
+		vec![StateDifference::MissingCandidate { candidate_hash, in_a: true }],
+	);
+}
+
+#[test]
+fn diff_reports_missing_approval_entry() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let block_hash = Hash::repeat_byte(2);
+	let candidate_hash = CandidateHash(Hash::repeat_byte(3));
+
+	add_blocks_to_both(&store_a, &store_b, vec![
+		(1, make_block_entry(block_hash, Hash::repeat_byte(1), vec![(CoreIndex(0), candidate_hash)])),
+	]);
+
+	let mut candidate_entry = load_candidate_entry(&store_b, &candidate_hash).unwrap().unwrap();
+	candidate_entry.block_assignments.remove(&block_hash);
+	store_b.write_candidate_entry(&candidate_hash, &candidate_entry);
+
+	assert_eq!(
+		diff(&store_a, &store_b).unwrap(),
+		vec![StateDifference::MissingApprovalEntry { candidate_hash, block_hash, in_a: true }],
+	);
+}
+
+#[test]
+fn diff_reports_assignments() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let block_hash = Hash::repeat_byte(2);
+	let candidate_hash = CandidateHash(Hash::repeat_byte(3));
+
+	add_blocks_to_both(&store_a, &store_b, vec![
+		(1, make_block_entry(block_hash, Hash::repeat_byte(1), vec![(CoreIndex(0), candidate_hash)])),
+	]);
+
+	let mut candidate_entry = load_candidate_entry(&store_a, &candidate_hash).unwrap().unwrap();
+	assert!(candidate_entry.block_assignments.get_mut(&block_hash).unwrap().import_assignment(0, 5, 1));
+	store_a.write_candidate_entry(&candidate_hash, &candidate_entry);
+
+	assert_eq!(
+		diff(&store_a, &store_b).unwrap(),
+		vec![StateDifference::Assignments {
+			candidate_hash,
+			block_hash,
+			only_in_a: vec![(0, 5)],
+			only_in_b: vec![],
+		}],
+	);
+}
+
+#[test]
+fn diff_reports_approvals_of_shared_candidate_once() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let parent_hash = Hash::repeat_byte(1);
+	let block_hash_a = Hash::repeat_byte(2);
+	let block_hash_b = Hash::repeat_byte(3);
+	let candidate_hash = CandidateHash(Hash::repeat_byte(4));
+
+	add_blocks_to_both(&store_a, &store_b, vec![
+		(1, make_block_entry(block_hash_a, parent_hash, vec![(CoreIndex(0), candidate_hash)])),
+		(1, make_block_entry(block_hash_b, parent_hash, vec![(CoreIndex(0), candidate_hash)])),
+	]);
+
+	let mut candidate_entry = load_candidate_entry(&store_a, &candidate_hash).unwrap().unwrap();
+	candidate_entry.approvals.set(3, true);
+	store_a.write_candidate_entry(&candidate_hash, &candidate_entry);
+
+	// both blocks differ, but the approvals of the candidate are only reported once.
+	assert_ne!(state_hash(&store_a, &block_hash_a).unwrap(), state_hash(&store_b, &block_hash_a).unwrap());
+	assert_ne!(state_hash(&store_a, &block_hash_b).unwrap(), state_hash(&store_b, &block_hash_b).unwrap());
+
+	assert_eq!(
+		diff(&store_a, &store_b).unwrap(),
+		vec![StateDifference::Approvals {
+			candidate_hash,
+			only_in_a: vec![3],
+			only_in_b: vec![],
+		}],
+	);
+}
+
+#[test]
+fn diff_ignores_sibling_import_order() {
+	let store_a = TestStore::default();
+	let store_b = TestStore::default();
+
+	let block_hash = Hash::repeat_byte(2);
+	let block_entry = make_block_entry(block_hash, Hash::repeat_byte(1), Vec::new());
+	let child_entry_1 = make_block_entry(Hash::repeat_byte(4), block_hash, Vec::new());
+	let child_entry_2 = make_block_entry(Hash::repeat_byte(3), block_hash, Vec::new());
+
+	for &(store, ref children) in &[
+		(&store_a, vec![child_entry_1.clone(), child_entry_2.clone()]),
+		(&store_b, vec![child_entry_2.clone(), child_entry_1.clone()]),
+	] {
+		add_block_entry(store, 1, block_entry.clone(), 10, |_| None).unwrap();
+
+		for child in children {
+			add_block_entry(store, 2, child.clone(), 10, |_| None).unwrap();
+		}
+	}
+
+	assert!(diff(&store_a, &store_b).unwrap().is_empty());
+}
+
+#[test]
+fn approval_entry_no_show_validators() {
+	let no_show_duration = 10;
+
+	let mut approval_entry = ApprovalEntry {
+		tranches: Vec::new(),
+		backing_group: GroupIndex(0),
+		next_wakeup: 0,
+		our_assignment: None,
+		assignments: make_bitvec(10),
+		approved: false,
+	};
+
+	approval_entry.import_assignment(0, 4, 0);
+	approval_entry.import_assignment(0, 1, 0);
+	approval_entry.import_assignment(0, 2, 1);
+	approval_entry.import_assignment(1, 3, 5);
+	approval_entry.import_assignment(1, 7, 6);
+
+	let mut approvals = make_bitvec(10);
+	approvals.set(2, true);
+	approvals.set(7, true);
+
+	assert!(approval_entry.no_show_validators(&approvals, 9, no_show_duration).is_empty());
+	assert_eq!(approval_entry.no_show_validators(&approvals, 10, no_show_duration), vec![1, 4]);
+	assert_eq!(approval_entry.no_show_validators(&approvals, 15, no_show_duration), vec![1, 4, 3]);
+	assert_eq!(approval_entry.no_show_validators(&approvals, 100, no_show_duration), vec![1, 4, 3]);
+}
+
+#[test]
+fn canonicalize_works() {
+	let store = TestStore::default();
+
+	//   -> B1 -> C1 -> D1
+	// A -> B2 -> C2 -> D2
+	//
+	// We'll canonicalize C1. Everything except D1 should disappear.
+	//
+	// Candidates:
+	// Cand1 in B2
+	// Cand2 in C2
+	// Cand3 in C2 and D1
+	// Cand4 in D1
+	// Cand5 in D2
+	// Only Cand3 and Cand4 should remain after canonicalize.
+
+	let n_validators = 10;
+
+	store.write_stored_blocks(StoredBlockRange(1, 5));
+
+	let genesis = Hash::repeat_byte(0);
+
+	let block_hash_a = Hash::repeat_byte(1);
+	let block_hash_b1 = Hash::repeat_byte(2);
+	let block_hash_b2 = Hash::repeat_byte(3);
+	let block_hash_c1 = Hash::repeat_byte(4);
+	let block_hash_c2 = Hash::repeat_byte(5);
+	let block_hash_d1 = Hash::repeat_byte(6);
+	let block_hash_d2 = Hash::repeat_byte(7);
+
+	let cand_hash_1 = CandidateHash(Hash::repeat_byte(10));
+	let cand_hash_2 = CandidateHash(Hash::repeat_byte(11));
+	let cand_hash_3 = CandidateHash(Hash::repeat_byte(12));
+	let cand_hash_4 = CandidateHash(Hash::repeat_byte(13));
+	let cand_hash_5 = CandidateHash(Hash::repeat_byte(15));
+
+	let block_entry_a = make_block_entry(block_hash_a, genesis, Vec::new());
+	let block_entry_b1 = make_block_entry(block_hash_b1, block_hash_a, Vec::new());
+	let block_entry_b2 = make_block_entry(
+		block_hash_b2,
+		block_hash_a,
+		vec![(CoreIndex(0), cand_hash_1)],
+	);
+	let block_entry_c1 = make_block_entry(block_hash_c1, block_hash_b1, Vec::new());
+	let block_entry_c2 = make_block_entry(
+		block_hash_c2,
+		block_hash_b2,
+		vec![(CoreIndex(0), cand_hash_2), (CoreIndex(1), cand_hash_3)],
 	);
 	let block_entry_d1 = make_block_entry(
 		block_hash_d1,
+		block_hash_c1,
 		vec![(CoreIndex(0), cand_hash_3), (CoreIndex(1), cand_hash_4)],
 	);
-	let block_entry_d2 = make_block_entry(block_hash_d2, vec![(CoreIndex(0), cand_hash_5)]);
+	let block_entry_d2 = make_block_entry(
+		block_hash_d2,
+		block_hash_c2,
+		vec![(CoreIndex(0), cand_hash_5)],
+	);
 
 
 	let candidate_info = {
@@ -413,19 +1584,18 @@ fn canonicalize_works() {
 
 	// now insert all the blocks.
 	let blocks = vec![
-		(genesis, 1, block_entry_a.clone()),
-		(block_hash_a, 2, block_entry_b1.clone()),
-		(block_hash_a, 2, block_entry_b2.clone()),
-		(block_hash_b1, 3, block_entry_c1.clone()),
-		(block_hash_b2, 3, block_entry_c2.clone()),
-		(block_hash_c1, 4, block_entry_d1.clone()),
-		(block_hash_c2, 4, block_entry_d2.clone()),
+		(1, block_entry_a.clone()),
+		(2, block_entry_b1.clone()),
+		(2, block_entry_b2.clone()),
+		(3, block_entry_c1.clone()),
+		(3, block_entry_c2.clone()),
+		(4, block_entry_d1.clone()),
+		(4, block_entry_d2.clone()),
 	];
 
-	for (parent_hash, number, block_entry) in blocks {
+	for (number, block_entry) in blocks {
 		add_block_entry(
 			&store,
-			parent_hash,
 			number,
 			block_entry,
 			n_validators,
@@ -22,6 +22,7 @@
 //! been sufficiently approved to finalize.
 
 mod aux_schema;
+mod time;
 
 /// A base unit of time, starting from the unix epoch, split into half-second intervals.
 type Tick = u64;
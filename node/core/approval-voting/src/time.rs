@@ -0,0 +1,86 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Time utilities for approval voting.
+
+use polkadot_primitives::v1::{Hash, BlakeTwo256, HashT};
+
+use super::Tick;
+
+/// Deterministic jitter in the range `0..max_ticks`, derived from a seed hash and an attempt
+/// number.
+///
+/// The seed is usually a candidate or block hash. Identical inputs always give identical
+/// jitter, so schedules built from it are reproducible. Returns 0 if `max_ticks` is 0.
+// TODO: use for recovery retries, quarantine retries and GC sweeps once those are scheduled
+// by the subsystem.
+#[allow(dead_code)]
+pub(crate) fn jitter(seed_hash: &Hash, attempt: u32, max_ticks: Tick) -> Tick {
+	if max_ticks == 0 { return 0 }
+
+	let digest = BlakeTwo256::hash_of(&(seed_hash, attempt));
+	let mut bytes = [0u8; 8];
+	bytes.copy_from_slice(&digest.as_ref()[..8]);
+
+	u64::from_le_bytes(bytes) % max_ticks
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn jitter_is_pinned() {
+		assert_eq!(jitter(&Hash::repeat_byte(1), 0, 100), 21);
+		assert_eq!(jitter(&Hash::repeat_byte(1), 1, 100), 48);
+		assert_eq!(jitter(&Hash::repeat_byte(2), 0, 100), 46);
+		assert_eq!(jitter(&Hash::repeat_byte(0xff), 7, 1000), 877);
+	}
+
+	#[test]
+	fn jitter_is_deterministic_and_bounded() {
+		for attempt in 0..32 {
+			let a = jitter(&Hash::repeat_byte(3), attempt, 10);
+			let b = jitter(&Hash::repeat_byte(3), attempt, 10);
+			assert_eq!(a, b);
+			assert!(a < 10);
+		}
+
+		assert_eq!(jitter(&Hash::repeat_byte(3), 0, 0), 0);
+	}
+
+	#[test]
+	fn jitter_is_roughly_uniform() {
+		const BUCKETS: usize = 10;
+		const SAMPLES: u32 = 10_000;
+
+		let mut counts = [0u32; BUCKETS];
+		for i in 0..SAMPLES {
+			let seed = BlakeTwo256::hash_of(&i);
+			counts[jitter(&seed, 0, BUCKETS as Tick) as usize] += 1;
+		}
+
+		let expected = SAMPLES / BUCKETS as u32;
+		for &count in &counts {
+			assert!(
+				count > expected * 8 / 10 && count < expected * 12 / 10,
+				"bucket count {} too far from {}",
+				count,
+				expected,
+			);
+		}
+	}
+}
@@ -71,6 +71,14 @@ pub struct AssignmentCert {
 	pub vrf: (VRFOutput, VRFProof),
 }
 
+impl AssignmentCert {
+	/// A compact, stable identifier of this cert, derived from the cert kind and the VRF
+	/// output and proof. Suitable for deduplication and logging.
+	pub fn fingerprint(&self) -> [u8; 32] {
+		sp_core::hashing::blake2_256(&self.encode())
+	}
+}
+
 /// An assignment crt which refers to the candidate under which the assignment is
 /// relevant by block hash.
 #[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
@@ -121,3 +129,43 @@ pub struct BlockApprovalMeta {
 	/// The consensus slot of the block.
 	pub slot: Slot,
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn make_cert(kind: AssignmentCertKind) -> AssignmentCert {
+		let ctx = schnorrkel::signing_context(RELAY_VRF_MODULO_CONTEXT);
+		let msg = b"WhenParachains?";
+		let mut prng = rand_core::OsRng;
+		let keypair = schnorrkel::Keypair::generate_with(&mut prng);
+		let (inout, proof, _) = keypair.vrf_sign(ctx.bytes(msg));
+		let out = inout.to_output();
+
+		AssignmentCert {
+			kind,
+			vrf: (VRFOutput(out), VRFProof(proof)),
+		}
+	}
+
+	#[test]
+	fn fingerprint_is_stable() {
+		let cert = make_cert(AssignmentCertKind::RelayVRFModulo { sample: 1 });
+		let decoded = AssignmentCert::decode(&mut &cert.encode()[..]).unwrap();
+
+		assert_eq!(cert.fingerprint(), cert.clone().fingerprint());
+		assert_eq!(cert.fingerprint(), decoded.fingerprint());
+	}
+
+	#[test]
+	fn fingerprint_differs_for_different_certs() {
+		let cert_a = make_cert(AssignmentCertKind::RelayVRFModulo { sample: 1 });
+		let cert_b = make_cert(AssignmentCertKind::RelayVRFModulo { sample: 1 });
+
+		let mut cert_c = cert_a.clone();
+		cert_c.kind = AssignmentCertKind::RelayVRFModulo { sample: 2 };
+
+		assert_ne!(cert_a.fingerprint(), cert_b.fingerprint());
+		assert_ne!(cert_a.fingerprint(), cert_c.fingerprint());
+	}
+}